@@ -0,0 +1,143 @@
+//! WASI preview2 / component-model runtime support.
+//!
+//! This path is selected automatically when [`crate::WasmtimeEngineProvider`]
+//! is handed a component rather than a core module (see [`crate::is_component`]).
+//! The `__guest_call` export is invoked exactly like the core ABI: it takes the
+//! operation and message lengths and returns an `i32` status.
+//!
+//! # Limitations
+//!
+//! This mode currently links **only** WASI preview2 and any embedder-supplied
+//! [`HostFactor`]s. The waPC host ABI (`__host_call`, `__guest_request`,
+//! `__console_log`, …) is *not* yet exposed to components — those imports are
+//! core-module callbacks and have no component-model equivalent here, so a
+//! component importing them fails to instantiate. Host calls from component
+//! guests must instead be provided via a [`HostFactor`].
+//!
+//! The fuel, epoch and [`wasmtime::StoreLimits`] controls from the core path are
+//! likewise not applied to the component store. Because silently ignoring them
+//! would be a sandbox-escape hazard, the provider **refuses** to load a
+//! component when any of `with_fuel` / `with_epoch_timeout` / `with_memory_limit`
+//! / `with_table_elements` / `with_max_instances` is configured, rather than
+//! running the guest unconstrained.
+
+use std::error::Error;
+use std::sync::Arc;
+
+use wapc::ModuleState;
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store};
+use wasmtime_wasi::preview2::{ResourceTable, WasiCtx, WasiCtxBuilder, WasiView};
+
+/// Store data backing a component instance: the preview2 WASI context and its
+/// resource table. The waPC [`ModuleState`] lives on [`ComponentRuntime`] rather
+/// than here because host-ABI imports are not wired into the component linker
+/// yet (see the module-level limitations).
+pub(crate) struct WapcComponentStore {
+    wasi: WasiCtx,
+    table: ResourceTable,
+}
+
+impl WasiView for WapcComponentStore {
+    fn table(&mut self) -> &mut ResourceTable {
+        &mut self.table
+    }
+
+    fn ctx(&mut self) -> &mut WasiCtx {
+        &mut self.wasi
+    }
+}
+
+/// A pluggable preview2 capability that an embedder can compose onto the store.
+///
+/// Factors are added to the component [`Linker`] in registration order,
+/// following wasmtime's factored host-component approach, so embedders can layer
+/// extra capabilities (clocks, random, sockets, custom shims) on top of the
+/// default WASI bindings without forking the provider.
+pub trait HostFactor: Send + Sync {
+    /// Adds this factor's host bindings to the component linker.
+    fn add_to_linker(&self, linker: &mut Linker<WapcComponentStore>) -> anyhow::Result<()>;
+}
+
+/// Drives a waPC guest that ships as a component rather than a core module.
+pub(crate) struct ComponentRuntime {
+    store: Store<WapcComponentStore>,
+    guest_call: wasmtime::component::Func,
+    host: Arc<ModuleState>,
+}
+
+impl ComponentRuntime {
+    /// Compiles and instantiates `bytes` as a component, wiring preview2 WASI and
+    /// any registered [`HostFactor`]s into the linker.
+    pub(crate) fn instantiate(
+        engine: &Engine,
+        bytes: &[u8],
+        host: Arc<ModuleState>,
+        factors: &[Box<dyn HostFactor>],
+    ) -> Result<Self, Box<dyn Error>> {
+        let component = Component::new(engine, bytes)?;
+
+        let mut linker: Linker<WapcComponentStore> = Linker::new(engine);
+        wasmtime_wasi::preview2::command::sync::add_to_linker(&mut linker)?;
+        for factor in factors {
+            factor.add_to_linker(&mut linker)?;
+        }
+
+        let mut store = Store::new(
+            engine,
+            WapcComponentStore {
+                wasi: WasiCtxBuilder::new().build(),
+                table: ResourceTable::new(),
+            },
+        );
+        // The provider's engine enables `consume_fuel` and `epoch_interruption`,
+        // which leave a fresh store with 0 fuel and a 0 epoch deadline — the
+        // first guest instruction would trap. The component runtime does not
+        // expose those controls yet, so run unmetered with an unbounded deadline.
+        store.set_fuel(u64::MAX)?;
+        store.set_epoch_deadline(u64::MAX);
+
+        let instance = linker.instantiate(&mut store, &component)?;
+        let guest_call = instance
+            .get_func(&mut store, wapc::WapcFunctions::GUEST_CALL)
+            .ok_or("component did not export a __guest_call function")?;
+
+        Ok(ComponentRuntime {
+            store,
+            guest_call,
+            host,
+        })
+    }
+
+    /// Returns a handle to the waPC [`ModuleState`] so a hot swap can carry it over.
+    pub(crate) fn host(&self) -> Arc<ModuleState> {
+        self.host.clone()
+    }
+
+    /// Invokes the guest's `__guest_call`, mapping traps onto the same
+    /// `set_guest_error` / `Ok(0)` error path the core runtime uses.
+    pub(crate) fn call(&mut self, op_length: i32, msg_length: i32) -> Result<i32, Box<dyn Error>> {
+        let params = [
+            wasmtime::component::Val::S32(op_length),
+            wasmtime::component::Val::S32(msg_length),
+        ];
+        let mut results = [wasmtime::component::Val::S32(0)];
+        match self
+            .guest_call
+            .call(&mut self.store, &params, &mut results)
+        {
+            Ok(()) => {
+                self.guest_call.post_return(&mut self.store)?;
+                match results[0] {
+                    wasmtime::component::Val::S32(v) => Ok(v),
+                    _ => Err("component __guest_call returned an unexpected type".into()),
+                }
+            }
+            Err(e) => {
+                error!("Failure invoking guest component handler: {:?}", e);
+                self.host.set_guest_error(e.to_string());
+                Ok(0)
+            }
+        }
+    }
+}
@@ -1,7 +1,12 @@
+use std::collections::HashMap;
 use std::error::Error;
+use std::time::Duration;
 
 use wapc::{ModuleState, WapcFunctions, WasiParams, WebAssemblyEngineProvider, HOST_NAMESPACE};
-use wasmtime::{AsContextMut, Engine, Extern, ExternType, Func, Instance, Linker, Module, Store};
+use wasmtime::{
+    AsContextMut, Engine, Extern, ExternType, Func, GuestProfiler, Instance, Linker, Module,
+    ProfilingStrategy, Store, UpdateDeadline,
+};
 #[cfg(feature = "wasi")]
 use wasmtime_wasi::WasiCtx;
 
@@ -17,6 +22,52 @@ extern crate log;
 mod callbacks;
 #[cfg(feature = "wasi")]
 mod wasi;
+#[cfg(feature = "component")]
+mod component;
+
+#[cfg(feature = "component")]
+pub use component::HostFactor;
+
+/// Returns `true` when `bytes` is a WebAssembly *component* rather than a core
+/// module. Both share the `\0asm` preamble but differ in the layer field: the
+/// byte at offset 6 is `0x01` for a component and `0x00` for a core module.
+#[cfg(feature = "component")]
+fn is_component(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && bytes[0..4] == *b"\0asm" && bytes[6] == 0x01
+}
+
+/// The epoch-based interruption subsystem ticks at this interval. Every tick a
+/// background thread calls [`Engine::increment_epoch`], so a deadline of `n`
+/// ticks roughly corresponds to `n * EPOCH_TICK` of wall-clock time.
+const EPOCH_TICK: Duration = Duration::from_millis(1);
+
+/// Callback invoked when a guest reaches its epoch deadline. Returning
+/// [`UpdateDeadline::Continue`] grants the guest another batch of ticks.
+/// [`UpdateDeadline::Yield`] needs an engine with async support, and guest
+/// calls here are synchronous, so it cannot be used. This mirrors wasmtime's `Store::epoch_deadline_callback` contract and lets
+/// long-running-but-legitimate modules extend their deadline instead of being
+/// killed.
+pub type UpdateDeadlineFn = dyn Fn() -> UpdateDeadline + Send + Sync;
+
+/// Holds the epoch deadline configured by [`WasmtimeEngineProvider::with_epoch_timeout`]
+/// together with the handle of the background thread driving the epoch counter.
+struct EpochDeadlines {
+    /// Number of epoch ticks a single `__guest_call` invocation is allowed to run.
+    ticks: u64,
+    /// Set to `true` by [`Drop`] to stop the ticker thread.
+    stop: Arc<std::sync::atomic::AtomicBool>,
+    /// Handle of the thread calling [`Engine::increment_epoch`] on every tick.
+    ticker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for EpochDeadlines {
+    fn drop(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+    }
+}
 
 struct EngineInner {
     instance: Arc<RwLock<Instance>>,
@@ -27,6 +78,44 @@ struct EngineInner {
 struct WapcStore {
     #[cfg(feature = "wasi")]
     wasi_ctx: WasiCtx,
+    /// Caps on linear-memory, table and instance growth enforced by the store.
+    /// Defaults to wasmtime's default limits (no memory or table-element
+    /// ceiling) until configured through the `with_memory_limit` /
+    /// `with_table_elements` / `with_max_instances` builder methods.
+    limits: wasmtime::StoreLimits,
+    /// Sampling guest profiler, present only when guest profiling is enabled.
+    /// Sampled from the epoch deadline callback and taken out of the store data
+    /// for the duration of each sample to avoid aliasing the store.
+    profiler: Option<GuestProfiler>,
+}
+
+/// Tuning parameters for wasmtime's pooling instance allocator.
+///
+/// The pooling allocator pre-reserves a fixed number of instance, memory and
+/// table slots up front and hands them back out — combined with copy-on-write
+/// image reuse this turns each instantiation into a cheap slot reset instead of
+/// fresh `mmap`s, which pays off in the hot-swap/`replace` and high-throughput
+/// call workflows.
+pub struct PoolingConfig {
+    /// Maximum number of concurrently allocated instances (and, by extension,
+    /// the number of pre-reserved memory and table slots).
+    pub total_instances: u32,
+    /// Maximum linear-memory size, in bytes, reserved for each memory slot.
+    pub max_memory_size: usize,
+    /// Maximum number of table elements reserved for each table slot.
+    pub table_elements: usize,
+}
+
+impl Default for PoolingConfig {
+    fn default() -> Self {
+        // Conservative defaults: a handful of slots, 128 MiB of memory and 10k
+        // table elements each — enough for a single hot-swapped module.
+        PoolingConfig {
+            total_instances: 16,
+            max_memory_size: 128 * 1024 * 1024,
+            table_elements: 10_000,
+        }
+    }
 }
 
 /// A waPC engine provider that encapsulates the Wasmtime WebAssembly runtime
@@ -36,12 +125,30 @@ pub struct WasmtimeEngineProvider {
     store: Store<WapcStore>,
     engine: Engine,
     linker: Linker<WapcStore>,
+    wasi: Option<WasiParams>,
+    epoch_deadlines: Option<EpochDeadlines>,
+    epoch_deadline_callback: Option<Arc<UpdateDeadlineFn>>,
+    fuel: Option<u64>,
+    last_fuel_consumed: Option<u64>,
+    memory_limit: Option<usize>,
+    table_elements: Option<usize>,
+    max_instances: Option<usize>,
+    guest_profiler_path: Option<std::path::PathBuf>,
+    host_imports: HashMap<(String, String), Extern>,
+    #[cfg(feature = "component")]
+    host_factors: Vec<Box<dyn HostFactor>>,
+    #[cfg(feature = "component")]
+    component: Option<component::ComponentRuntime>,
 }
 
 impl WasmtimeEngineProvider {
     /// Creates a new instance of a [WasmtimeEngineProvider].
     pub fn new(buf: &[u8], wasi: Option<WasiParams>) -> WasmtimeEngineProvider {
-        let engine = Engine::default();
+        let mut config = wasmtime::Config::new();
+        config.epoch_interruption(true);
+        config.consume_fuel(true);
+        // `Config::new` with these options enabled cannot fail to build an engine.
+        let engine = Engine::new(&config).unwrap();
         Self::new_with_engine(buf, engine, wasi)
     }
 
@@ -54,6 +161,8 @@ impl WasmtimeEngineProvider {
     ) -> anyhow::Result<WasmtimeEngineProvider> {
         let mut config = wasmtime::Config::new();
         config.strategy(wasmtime::Strategy::Cranelift)?;
+        config.epoch_interruption(true);
+        config.consume_fuel(true);
         if let Some(cache) = cache_path {
             config.cache_config_load(cache)?;
         } else if let Err(e) = config.cache_config_load_default() {
@@ -63,27 +172,40 @@ impl WasmtimeEngineProvider {
         Ok(Self::new_with_engine(buf, engine, wasi))
     }
 
+    /// Creates a new instance of a [WasmtimeEngineProvider] backed by wasmtime's
+    /// pooling instance allocator.
+    ///
+    /// Instantiating a module reuses pre-reserved, copy-on-write memory slots
+    /// rather than mapping fresh regions on every `init`/`replace`, which is a
+    /// win for hosts that swap modules frequently or run many short-lived calls.
+    pub fn new_with_pool(
+        buf: &[u8],
+        wasi: Option<WasiParams>,
+        pool: PoolingConfig,
+    ) -> anyhow::Result<WasmtimeEngineProvider> {
+        let mut pooling = wasmtime::PoolingAllocationConfig::default();
+        pooling
+            .total_core_instances(pool.total_instances)
+            .total_memories(pool.total_instances)
+            .total_tables(pool.total_instances)
+            .max_memory_size(pool.max_memory_size)
+            .table_elements(pool.table_elements);
+
+        let mut config = wasmtime::Config::new();
+        config.epoch_interruption(true);
+        config.consume_fuel(true);
+        // Reuse the already-initialized memory image across instantiations.
+        config.memory_init_cow(true);
+        config.allocation_strategy(wasmtime::InstanceAllocationStrategy::Pooling(pooling));
+
+        let engine = Engine::new(&config)?;
+        Ok(Self::new_with_engine(buf, engine, wasi))
+    }
+
     /// Creates a new instance of a [WasmtimeEngineProvider] from a separately created [wasmtime::Engine].
     #[allow(unused)]
     pub fn new_with_engine(buf: &[u8], engine: Engine, wasi: Option<WasiParams>) -> Self {
-        let mut linker: Linker<WapcStore> = Linker::new(&engine);
-
-        cfg_if::cfg_if! {
-          if #[cfg(feature = "wasi")] {
-            wasmtime_wasi::add_to_linker(&mut linker, |s| &mut s.wasi_ctx).unwrap();
-            let wasi_params = wasi.unwrap_or_default();
-            let wasi_ctx = wasi::init_ctx(
-                &wasi::compute_preopen_dirs(&wasi_params.preopened_dirs, &wasi_params.map_dirs)
-                    .unwrap(),
-                &wasi_params.argv,
-                &wasi_params.env_vars,
-            )
-            .unwrap();
-            let store = Store::new(&engine, WapcStore { wasi_ctx });
-          } else {
-            let store = Store::new(&engine, WapcStore {});
-          }
-        };
+        let (store, linker) = build_store_and_linker(&engine, &wasi);
 
         WasmtimeEngineProvider {
             inner: None,
@@ -91,18 +213,348 @@ impl WasmtimeEngineProvider {
             store,
             engine,
             linker,
+            wasi,
+            epoch_deadlines: None,
+            epoch_deadline_callback: None,
+            fuel: None,
+            last_fuel_consumed: None,
+            memory_limit: None,
+            table_elements: None,
+            max_instances: None,
+            guest_profiler_path: None,
+            host_imports: HashMap::new(),
+            #[cfg(feature = "component")]
+            host_factors: Vec::new(),
+            #[cfg(feature = "component")]
+            component: None,
+        }
+    }
+
+    /// Registers a preview2 "host factor" that composes additional
+    /// component-model capabilities (clocks, random, sockets, …) onto the store.
+    ///
+    /// Factors are added to the [`component::Linker`] in registration order when
+    /// a component module is instantiated, following the factored host-component
+    /// approach. Has no effect for core modules.
+    #[cfg(feature = "component")]
+    #[must_use]
+    pub fn with_host_factor(mut self, factor: Box<dyn HostFactor>) -> Self {
+        self.host_factors.push(factor);
+        self
+    }
+
+    /// Registers an additional host-provided import so that modules importing a
+    /// namespace beyond the built-in waPC and WASI ones can still be instantiated.
+    ///
+    /// Resolvers supplied this way are consulted by [`arrange_imports`] before it
+    /// gives up, letting embedders satisfy custom imports (e.g. a key-value or
+    /// HTTP shim) without the provider aborting. The [`Extern`] must be created
+    /// against this provider's store.
+    #[must_use]
+    pub fn with_host_import(
+        mut self,
+        module: impl Into<String>,
+        name: impl Into<String>,
+        import: impl Into<Extern>,
+    ) -> Self {
+        self.host_imports
+            .insert((module.into(), name.into()), import.into());
+        self
+    }
+
+    /// Rejects modules that try to grow linear memory past `bytes`.
+    ///
+    /// This guards the host against a guest exhausting its RAM; growth attempts
+    /// beyond the ceiling fail inside the guest instead of allocating on the host.
+    #[must_use]
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self.apply_store_limits();
+        self
+    }
+
+    /// Caps the total number of table elements a module may allocate.
+    #[must_use]
+    pub fn with_table_elements(mut self, elements: usize) -> Self {
+        self.table_elements = Some(elements);
+        self.apply_store_limits();
+        self
+    }
+
+    /// Caps the number of instances that may live in the store at once.
+    #[must_use]
+    pub fn with_max_instances(mut self, instances: usize) -> Self {
+        self.max_instances = Some(instances);
+        self.apply_store_limits();
+        self
+    }
+
+    /// (Re)builds the [`wasmtime::StoreLimits`] from the configured ceilings and
+    /// installs them into the store data the limiter reads from.
+    fn apply_store_limits(&mut self) {
+        let mut builder = wasmtime::StoreLimitsBuilder::new();
+        if let Some(bytes) = self.memory_limit {
+            builder = builder.memory_size(bytes);
+        }
+        if let Some(elements) = self.table_elements {
+            builder = builder.table_elements(elements);
+        }
+        if let Some(instances) = self.max_instances {
+            builder = builder.instances(instances);
+        }
+        self.store.data_mut().limits = builder.build();
+    }
+
+    /// Returns the name of the first configured sandbox control that the
+    /// component runtime does not yet enforce, or `None` when none are set.
+    ///
+    /// Used to refuse loading an untrusted component when the host asked for a
+    /// limit that would silently not apply — dropping it would be a security
+    /// regression, not a missing convenience.
+    #[cfg(feature = "component")]
+    fn unsupported_component_control(&self) -> Option<&'static str> {
+        if self.fuel.is_some() {
+            Some("with_fuel")
+        } else if self.memory_limit.is_some() {
+            Some("with_memory_limit")
+        } else if self.table_elements.is_some() {
+            Some("with_table_elements")
+        } else if self.max_instances.is_some() {
+            Some("with_max_instances")
+        } else if self.epoch_deadlines.is_some() {
+            Some("with_epoch_timeout")
+        } else {
+            None
+        }
+    }
+
+    /// Enables a native JIT profiler so that compiled guest code shows up in
+    /// external tooling.
+    ///
+    /// [`ProfilingStrategy::PerfMap`] and [`ProfilingStrategy::JitDump`] make the
+    /// guest's JIT'd functions visible to `perf`/`samply`. Because the profiler
+    /// is an engine-level setting, this rebuilds the underlying engine; call it
+    /// before the other builder methods and before [`init`]. For the sampling
+    /// guest profiler that produces a Firefox-profiler trace, use
+    /// [`with_guest_profiling`] instead.
+    ///
+    /// [`init`]: WebAssemblyEngineProvider::init
+    /// [`with_guest_profiling`]: WasmtimeEngineProvider::with_guest_profiling
+    #[must_use]
+    pub fn with_profiling(mut self, strategy: ProfilingStrategy) -> Self {
+        let mut config = wasmtime::Config::new();
+        config.epoch_interruption(true);
+        config.consume_fuel(true);
+        config.profiler(strategy);
+        // A `Config` that only toggles these features always yields an engine.
+        self.engine = Engine::new(&config).unwrap();
+        let (store, linker) = build_store_and_linker(&self.engine, &self.wasi);
+        self.store = store;
+        self.linker = linker;
+        // The fresh store starts with default limits (no memory or table-element
+        // ceiling); re-apply any
+        // ceilings configured before this call so they are not silently dropped.
+        self.apply_store_limits();
+        self
+    }
+
+    /// Enables the sampling [`GuestProfiler`], writing a Firefox-profiler
+    /// compatible JSON trace to `path` when the provider is dropped or when
+    /// [`finish_profile`] is called.
+    ///
+    /// The profiler is sampled on the same epoch tick used for execution
+    /// timeouts, so [`with_epoch_timeout`] must also be configured; `init`
+    /// returns an error otherwise.
+    ///
+    /// [`finish_profile`]: WasmtimeEngineProvider::finish_profile
+    /// [`with_epoch_timeout`]: WasmtimeEngineProvider::with_epoch_timeout
+    #[must_use]
+    pub fn with_guest_profiling(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.guest_profiler_path = Some(path.into());
+        self
+    }
+
+    /// Flushes the sampling guest profile to the configured path, consuming the
+    /// profiler. Subsequent calls are no-ops.
+    pub fn finish_profile(&mut self) -> Result<(), Box<dyn Error>> {
+        let (Some(path), Some(profiler)) = (
+            self.guest_profiler_path.clone(),
+            self.store.data_mut().profiler.take(),
+        ) else {
+            return Ok(());
+        };
+        let file = std::fs::File::create(&path)?;
+        profiler.finish(std::io::BufWriter::new(file))?;
+        info!("Wrote guest profile to {}", path.display());
+        Ok(())
+    }
+
+    /// Caps the number of wasmtime "fuel" units a guest may consume.
+    ///
+    /// Fuel is a deterministic, wall-clock-independent measure of executed
+    /// instructions. The limit is replenished before each module start and
+    /// before every `__guest_call`; a guest that runs out traps and is surfaced
+    /// as a `"out of fuel"` guest error. Pair with [`fuel_consumed`] to bill or
+    /// log the instruction count of each invocation.
+    ///
+    /// [`fuel_consumed`]: WasmtimeEngineProvider::fuel_consumed
+    #[must_use]
+    pub fn with_fuel(mut self, limit: u64) -> Self {
+        self.fuel = Some(limit);
+        self
+    }
+
+    /// Returns the amount of fuel consumed by the most recent `__guest_call`,
+    /// or `None` when fuel metering is disabled or no call has run yet.
+    #[must_use]
+    pub fn fuel_consumed(&self) -> Option<u64> {
+        self.last_fuel_consumed
+    }
+
+    /// Refills the store's fuel to the configured limit, if any.
+    fn refill_fuel(&mut self) -> Result<(), Box<dyn Error>> {
+        // `consume_fuel` is enabled engine-wide, and a fuel-enabled store starts
+        // with *zero* fuel — which would trap on the first instruction. When no
+        // limit was requested we top the store up to the maximum so default
+        // providers run unmetered.
+        let limit = self.fuel.unwrap_or(u64::MAX);
+        self.store.set_fuel(limit)?;
+        Ok(())
+    }
+
+    /// Enforces an upper bound on the execution time of every `__guest_call`
+    /// invocation using wasmtime's epoch-based interruption.
+    ///
+    /// A background thread increments the engine epoch every [`EPOCH_TICK`];
+    /// before each guest call the store's deadline is set so that a call running
+    /// longer than `timeout` traps and is surfaced as a `"execution timed out"`
+    /// guest error. The engine must have been created with epoch interruption
+    /// enabled — all of this crate's constructors do so.
+    #[must_use]
+    pub fn with_epoch_timeout(mut self, timeout: Duration) -> Self {
+        let ticks = (timeout.as_millis() / EPOCH_TICK.as_millis().max(1)).max(1) as u64;
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let ticker = {
+            let engine = self.engine.clone();
+            let stop = stop.clone();
+            std::thread::spawn(move || {
+                while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                    std::thread::sleep(EPOCH_TICK);
+                    engine.increment_epoch();
+                }
+            })
+        };
+        self.epoch_deadlines = Some(EpochDeadlines {
+            ticks,
+            stop,
+            ticker: Some(ticker),
+        });
+        self
+    }
+
+    /// Registers a callback invoked whenever a guest reaches its epoch deadline.
+    ///
+    /// The callback can grant the guest more time ([`UpdateDeadline::Continue`]),
+    /// letting long-running-but-legitimate modules extend their deadline instead
+    /// of being killed. [`UpdateDeadline::Yield`] needs an async-enabled engine
+    /// and cannot be used with these synchronous calls. Has no effect unless [`with_epoch_timeout`] is also set.
+    ///
+    /// [`with_epoch_timeout`]: WasmtimeEngineProvider::with_epoch_timeout
+    #[must_use]
+    pub fn with_update_deadline_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn() -> UpdateDeadline + Send + Sync + 'static,
+    {
+        self.epoch_deadline_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Arms the epoch deadline for the next guest invocation, installing the
+    /// optional [`UpdateDeadlineFn`] callback on the store.
+    fn arm_epoch_deadline(&mut self) {
+        let Some(deadlines) = &self.epoch_deadlines else {
+            // `epoch_interruption` is enabled engine-wide, so a store left at its
+            // default deadline (0) would trap immediately. With no timeout
+            // configured, arm an effectively unbounded deadline instead.
+            self.store.set_epoch_deadline(u64::MAX);
+            self.store.epoch_deadline_trap();
+            return;
+        };
+        let ticks = deadlines.ticks;
+        self.store.set_epoch_deadline(ticks);
+
+        // When the sampling guest profiler is active it piggybacks on the epoch
+        // counter: take a sample on *every* tick (not once per timeout period)
+        // and extend the deadline by a single tick so the guest keeps running
+        // (profiling takes precedence over the timeout).
+        if self.guest_profiler_path.is_some() {
+            self.store.set_epoch_deadline(1);
+            let mut last = std::time::Instant::now();
+            self.store.epoch_deadline_callback(move |mut store| {
+                if let Some(mut profiler) = store.data_mut().profiler.take() {
+                    let now = std::time::Instant::now();
+                    let elapsed = now.duration_since(last);
+                    last = now;
+                    profiler.sample(&store, elapsed);
+                    store.data_mut().profiler = Some(profiler);
+                }
+                Ok(UpdateDeadline::Continue(1))
+            });
+            return;
+        }
+
+        match &self.epoch_deadline_callback {
+            Some(callback) => {
+                let callback = callback.clone();
+                self.store
+                    .epoch_deadline_callback(move |_store| Ok(callback()));
+            }
+            None => self.store.epoch_deadline_trap(),
+        }
+    }
+}
+
+impl Drop for WasmtimeEngineProvider {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish_profile() {
+            error!("Failed to flush guest profile: {:?}", e);
         }
     }
 }
 
 impl WebAssemblyEngineProvider for WasmtimeEngineProvider {
     fn init(&mut self, host: Arc<ModuleState>) -> Result<(), Box<dyn Error>> {
-        let instance = instance_from_buffer(
+        // When the buffer is a component and the feature is enabled, drive it
+        // through the preview2 component runtime instead of the core path.
+        #[cfg(feature = "component")]
+        if is_component(&self.modbytes) {
+            if let Some(control) = self.unsupported_component_control() {
+                return Err(format!(
+                    "`{control}` is not enforced for component modules; refusing to load an untrusted component without the requested sandbox control"
+                )
+                .into());
+            }
+            self.component = Some(component::ComponentRuntime::instantiate(
+                &self.engine,
+                &self.modbytes,
+                host,
+                &self.host_factors,
+            )?);
+            return Ok(());
+        }
+
+        // A module may run code via a wasm `start` section during
+        // instantiation, so the deadline and fuel must be armed beforehand too —
+        // not just around the explicit start functions in `initialize`.
+        self.arm_epoch_deadline();
+        self.refill_fuel()?;
+        let (instance, module) = instance_from_buffer(
             &mut self.store,
             &self.engine,
             &self.modbytes,
             host.clone(),
             &self.linker,
+            &self.host_imports,
         )?;
         let instance_ref = Arc::new(RwLock::new(instance));
         let gc = guest_call_fn(self.store.as_context_mut(), instance_ref.clone())?;
@@ -111,26 +563,76 @@ impl WebAssemblyEngineProvider for WasmtimeEngineProvider {
             guest_call_fn: gc,
             host,
         });
+        // Spin up the sampling guest profiler now that the module is loaded so
+        // that the epoch-tick callback has somewhere to record samples. The
+        // module is handed to the profiler so sampled frames can be symbolized.
+        // Sampling is driven by the epoch ticker, so a timeout must be set.
+        if self.guest_profiler_path.is_some() {
+            if self.epoch_deadlines.is_none() {
+                return Err(
+                    "guest profiling requires an epoch timeout; call `with_epoch_timeout` as well"
+                        .into(),
+                );
+            }
+            let profiler = GuestProfiler::new(
+                "wapc-guest",
+                EPOCH_TICK,
+                vec![("wapc-guest".to_owned(), module)],
+            );
+            self.store.data_mut().profiler = Some(profiler);
+        }
         self.initialize()?;
         Ok(())
     }
 
     fn call(&mut self, op_length: i32, msg_length: i32) -> Result<i32, Box<dyn Error>> {
-        let engine_inner = self.inner.as_ref().unwrap();
+        #[cfg(feature = "component")]
+        if let Some(runtime) = self.component.as_mut() {
+            return runtime.call(op_length, msg_length);
+        }
+
+        self.arm_epoch_deadline();
+        self.refill_fuel()?;
+        let host = self.inner.as_ref().unwrap().host.clone();
+        let guest_call_fn = self.inner.as_ref().unwrap().guest_call_fn;
         let mut results = [wasmtime::Val::I32(0); 1];
-        let call = engine_inner.guest_call_fn.call(
+        let call = guest_call_fn.call(
             &mut self.store,
             &[op_length.into(), msg_length.into()],
             &mut results,
         );
+        // Record how much fuel this invocation burned so hosts can bill or log it.
+        if let Some(limit) = self.fuel {
+            self.last_fuel_consumed = self
+                .store
+                .get_fuel()
+                .ok()
+                .map(|remaining| limit.saturating_sub(remaining));
+        }
         match call {
             Ok(()) => {
                 let result: i32 = results[0].i32().unwrap();
                 Ok(result)
             }
             Err(e) => {
+                // Both the epoch deadline and fuel exhaustion surface as wasmtime
+                // traps; report them with stable messages so hosts can tell a
+                // resource-limit hit apart from a genuine guest failure.
+                match e.downcast_ref::<wasmtime::Trap>() {
+                    Some(wasmtime::Trap::Interrupt) => {
+                        error!("Guest module execution timed out");
+                        host.set_guest_error("execution timed out".to_owned());
+                        return Ok(0);
+                    }
+                    Some(wasmtime::Trap::OutOfFuel) => {
+                        error!("Guest module ran out of fuel");
+                        host.set_guest_error("out of fuel".to_owned());
+                        return Ok(0);
+                    }
+                    _ => {}
+                }
                 error!("Failure invoking guest module handler: {:?}", e);
-                engine_inner.host.set_guest_error(e.to_string());
+                host.set_guest_error(e.to_string());
                 Ok(0)
             }
         }
@@ -142,14 +644,47 @@ impl WebAssemblyEngineProvider for WasmtimeEngineProvider {
             module.len()
         );
 
-        let new_instance = instance_from_buffer(
+        #[cfg(feature = "component")]
+        if is_component(module) {
+            if let Some(control) = self.unsupported_component_control() {
+                return Err(format!(
+                    "`{control}` is not enforced for component modules; refusing to load an untrusted component without the requested sandbox control"
+                )
+                .into());
+            }
+            let host = self
+                .component
+                .as_ref()
+                .map(component::ComponentRuntime::host)
+                .or_else(|| self.inner.as_ref().map(|i| i.host.clone()))
+                .ok_or("cannot replace module before init")?;
+            self.component = Some(component::ComponentRuntime::instantiate(
+                &self.engine,
+                module,
+                host,
+                &self.host_factors,
+            )?);
+            return Ok(());
+        }
+
+        // Arm the deadline and refill fuel before instantiation so a module with
+        // a wasm `start` section does not trap on the default 0-epoch/0-fuel store.
+        self.arm_epoch_deadline();
+        self.refill_fuel()?;
+        let (new_instance, _module) = instance_from_buffer(
             &mut self.store,
             &self.engine,
             module,
             self.inner.as_ref().unwrap().host.clone(),
             &self.linker,
+            &self.host_imports,
         )?;
         *self.inner.as_ref().unwrap().instance.write().unwrap() = new_instance;
+        // A core module replaces any component loaded by an earlier swap.
+        #[cfg(feature = "component")]
+        {
+            self.component = None;
+        }
 
         self.initialize()
     }
@@ -157,6 +692,11 @@ impl WebAssemblyEngineProvider for WasmtimeEngineProvider {
 
 impl WasmtimeEngineProvider {
     fn initialize(&mut self) -> Result<(), Box<dyn Error>> {
+        // Arm the deadline (bounded or unbounded) and refill fuel before the
+        // module's start functions run, otherwise they trap on the store's
+        // default epoch deadline / empty fuel tank.
+        self.arm_epoch_deadline();
+        self.refill_fuel()?;
         for starter in wapc::WapcFunctions::REQUIRED_STARTS.iter() {
             if let Some(ext) = self
                 .inner
@@ -176,50 +716,102 @@ impl WasmtimeEngineProvider {
     }
 }
 
+/// Builds the [`Store`] and [`Linker`] pair backing a provider for the given
+/// engine and optional WASI configuration. Kept separate so that engine-level
+/// reconfiguration (e.g. enabling a JIT profiler) can rebuild them in place.
+fn build_store_and_linker(
+    engine: &Engine,
+    wasi: &Option<WasiParams>,
+) -> (Store<WapcStore>, Linker<WapcStore>) {
+    let mut linker: Linker<WapcStore> = Linker::new(engine);
+
+    cfg_if::cfg_if! {
+      if #[cfg(feature = "wasi")] {
+        wasmtime_wasi::add_to_linker(&mut linker, |s| &mut s.wasi_ctx).unwrap();
+        let wasi_params = wasi.clone().unwrap_or_default();
+        let wasi_ctx = wasi::init_ctx(
+            &wasi::compute_preopen_dirs(&wasi_params.preopened_dirs, &wasi_params.map_dirs)
+                .unwrap(),
+            &wasi_params.argv,
+            &wasi_params.env_vars,
+        )
+        .unwrap();
+        let mut store = Store::new(engine, WapcStore { wasi_ctx, limits: wasmtime::StoreLimits::default(), profiler: None });
+      } else {
+        let _ = wasi;
+        let mut store = Store::new(engine, WapcStore { limits: wasmtime::StoreLimits::default(), profiler: None });
+      }
+    };
+    // Point the store at the `StoreLimits` embedded in its own data so that
+    // memory/table/instance growth is checked against the configured caps.
+    store.limiter(|s| &mut s.limits);
+
+    (store, linker)
+}
+
+/// Formats the error returned when a module import cannot be resolved against
+/// the built-in namespaces or any registered [`with_host_import`] resolver.
+///
+/// [`with_host_import`]: WasmtimeEngineProvider::with_host_import
+fn unresolved_import_error(module: &str, name: &str) -> String {
+    format!("import `{module}::{name}` was not found; register it with `with_host_import`")
+}
+
 fn instance_from_buffer(
     store: &mut Store<WapcStore>,
     engine: &Engine,
     buf: &[u8],
     state: Arc<ModuleState>,
     linker: &Linker<WapcStore>,
-) -> Result<Instance, Box<dyn Error>> {
-    let module = Module::new(engine, buf).unwrap();
-    let imports = arrange_imports(&module, state, store, linker);
-    Ok(wasmtime::Instance::new(store.as_context_mut(), &module, imports?.as_slice()).unwrap())
+    host_imports: &HashMap<(String, String), Extern>,
+) -> Result<(Instance, Module), Box<dyn Error>> {
+    let module = Module::new(engine, buf)?;
+    let imports = arrange_imports(&module, state, store, linker, host_imports)?;
+    let instance = wasmtime::Instance::new(store.as_context_mut(), &module, imports.as_slice())?;
+    Ok((instance, module))
 }
 
 /// wasmtime requires that the list of callbacks be "zippable" with the list
 /// of module imports. In order to ensure that both lists are in the same
 /// order, we have to loop through the module imports and instantiate the
 /// corresponding callback. We **cannot** rely on a predictable import order
-/// in the wasm module
-#[allow(clippy::unnecessary_wraps)]
+/// in the wasm module.
+///
+/// Imports from unknown namespaces are first looked up in `host_imports`
+/// (custom resolvers registered by the embedder); only if that fails too do we
+/// return a descriptive error naming the offending `module::name`.
 fn arrange_imports(
     module: &Module,
     host: Arc<ModuleState>,
     store: &mut impl AsContextMut<Data = WapcStore>,
     linker: &Linker<WapcStore>,
+    host_imports: &HashMap<(String, String), Extern>,
 ) -> Result<Vec<Extern>, Box<dyn Error>> {
-    Ok(module
-        .imports()
-        .filter_map(|imp| {
-            if let ExternType::Func(_) = imp.ty() {
-                match imp.module() {
-                    HOST_NAMESPACE => Some(callback_for_import(
-                        store.as_context_mut(),
-                        imp.name()?,
-                        host.clone(),
-                    )),
-                    WASI_SNAPSHOT_PREVIEW1_NAMESPACE | WASI_UNSTABLE_NAMESPACE => {
-                        linker.get_by_import(store.as_context_mut(), &imp)
-                    }
-                    other => panic!("import module `{}` was not found", other), //TODO: get rid of panic
-                }
-            } else {
-                None
+    let mut externs = Vec::new();
+    for imp in module.imports() {
+        let is_func = matches!(imp.ty(), ExternType::Func(_));
+        let resolved = match imp.module() {
+            HOST_NAMESPACE if is_func => {
+                let name = imp
+                    .name()
+                    .ok_or("waPC host import is missing a field name")?;
+                Some(callback_for_import(store.as_context_mut(), name, host.clone()))
             }
-        })
-        .collect())
+            WASI_SNAPSHOT_PREVIEW1_NAMESPACE | WASI_UNSTABLE_NAMESPACE => {
+                linker.get_by_import(store.as_context_mut(), &imp)
+            }
+            _ => None,
+        };
+        let resolved = resolved
+            .or_else(|| {
+                host_imports
+                    .get(&(imp.module().to_owned(), imp.name().unwrap_or_default().to_owned()))
+                    .cloned()
+            })
+            .ok_or_else(|| unresolved_import_error(imp.module(), imp.name().unwrap_or_default()))?;
+        externs.push(resolved);
+    }
+    Ok(externs)
 }
 
 fn callback_for_import(store: impl AsContextMut, import: &str, host: Arc<ModuleState>) -> Extern {
@@ -255,3 +847,36 @@ fn guest_call_fn(
         Err("Guest module did not export __guest_call function!".into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_limits_are_recorded() {
+        let provider = WasmtimeEngineProvider::new(&[], None)
+            .with_memory_limit(1024)
+            .with_table_elements(10)
+            .with_max_instances(2);
+        assert_eq!(provider.memory_limit, Some(1024));
+        assert_eq!(provider.table_elements, Some(10));
+        assert_eq!(provider.max_instances, Some(2));
+    }
+
+    #[test]
+    fn unresolved_import_error_names_offending_import() {
+        let msg = unresolved_import_error("env", "foo");
+        assert!(msg.contains("env::foo"), "message was: {msg}");
+        assert!(msg.contains("with_host_import"), "message was: {msg}");
+    }
+
+    #[test]
+    fn registered_host_import_is_recorded() {
+        let mut provider = WasmtimeEngineProvider::new(&[], None);
+        let func = Func::wrap(&mut provider.store, || {});
+        let provider = provider.with_host_import("env", "foo", func);
+        assert!(provider
+            .host_imports
+            .contains_key(&("env".to_owned(), "foo".to_owned())));
+    }
+}